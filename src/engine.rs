@@ -1,19 +1,30 @@
 use crate::module::{Module, ModuleId};
+use crate::patch::{Patch, PatchEdge, PatchModule};
+use crate::registry;
 use std::collections::HashMap;
 use petgraph::graph::{DiGraph, NodeIndex, EdgeIndex};
 use petgraph::algo::toposort;
+use petgraph::visit::EdgeRef;
 use crossbeam::queue::ArrayQueue;
 use std::sync::Arc;
 use parking_lot::Mutex;
 
+/// A single parameter edit destined for a module, queued up by the UI thread
+/// and applied by the audio worker at the start of its next block.
+pub struct ParamCommand {
+    pub module_id: ModuleId,
+    pub name: String,
+    pub value: f32,
+}
+
 pub struct AudioEngine {
     sample_rate: f64,
     buffer_size: usize,
     modules: HashMap<ModuleId, Arc<Mutex<dyn Module>>>,
     module_graph: DiGraph<ModuleId, (usize, usize)>, // (source_output, dest_input)
     processing_order: Vec<NodeIndex>,
-    audio_input_queue: Arc<ArrayQueue<Vec<f32>>>,
-    audio_output_queue: Arc<ArrayQueue<Vec<f32>>>,
+    command_queue: Arc<ArrayQueue<ParamCommand>>,
+    mix_bus: Option<ModuleId>,
 }
 
 impl AudioEngine {
@@ -24,11 +35,23 @@ impl AudioEngine {
             modules: HashMap::new(),
             module_graph: DiGraph::new(),
             processing_order: Vec::new(),
-            audio_input_queue: Arc::new(ArrayQueue::new(32)),
-            audio_output_queue: Arc::new(ArrayQueue::new(32)),
+            command_queue: Arc::new(ArrayQueue::new(256)),
+            mix_bus: None,
         }
     }
 
+    /// Designates `id` (typically a `Mixer`) as the final stereo output
+    /// stage, replacing the old "last topo node wins" behavior.
+    pub fn set_mix_bus(&mut self, id: ModuleId) {
+        self.mix_bus = Some(id);
+    }
+
+    /// Returns a handle to the lock-free parameter queue. Clone this into
+    /// the UI thread so parameter edits never block the audio worker.
+    pub fn command_queue(&self) -> Arc<ArrayQueue<ParamCommand>> {
+        self.command_queue.clone()
+    }
+
     pub fn add_module(&mut self, module: Arc<Mutex<dyn Module>>) -> ModuleId {
         let id = module.lock().id();
         let node_index = self.module_graph.add_node(id);
@@ -63,7 +86,13 @@ impl AudioEngine {
         }
     }
 
-    pub fn process(&mut self) -> Vec<f32> {
+    pub fn process(&mut self) -> Vec<(f32, f32)> {
+        while let Some(command) = self.command_queue.pop() {
+            if let Some(module) = self.modules.get(&command.module_id) {
+                module.lock().set_param(&command.name, command.value);
+            }
+        }
+
         let mut module_outputs: HashMap<ModuleId, Vec<f32>> = HashMap::new();
 
         for &node_index in &self.processing_order {
@@ -79,8 +108,12 @@ impl AudioEngine {
                 }
             }
 
-            // Process the module
-            let mut outputs = vec![0.0; self.buffer_size];
+            // Process the module. Output buffers are sized per output port
+            // count so multi-port modules (e.g. the stereo Mixer) can write
+            // each port's samples back-to-back, mirroring how inputs above
+            // are concatenated.
+            let output_port_count = self.modules[&module_id].lock().base.outputs.len().max(1);
+            let mut outputs = vec![0.0; self.buffer_size * output_port_count];
             if let Some(module) = self.modules.get(&module_id) {
                 module.lock().process(&inputs.concat(), &mut outputs);
             }
@@ -89,11 +122,173 @@ impl AudioEngine {
             module_outputs.insert(module_id, outputs);
         }
 
-        // Return the output of the last module in the processing order
+        // The designated mix bus (a Mixer) carries left/right channels back
+        // to back in its output buffer; split it into stereo frames.
+        if let Some(mix_bus_id) = self.mix_bus {
+            if let Some(buffer) = module_outputs.get(&mix_bus_id) {
+                let (left, right) = buffer.split_at(self.buffer_size);
+                return left.iter().zip(right.iter()).map(|(&l, &r)| (l, r)).collect();
+            }
+        }
+
+        // No mix bus configured: fall back to the last module in topological
+        // order, duplicated to both channels.
         if let Some(&last_module_id) = self.processing_order.last().map(|&n| &self.module_graph[n]) {
-            module_outputs.get(&last_module_id).cloned().unwrap_or_else(|| vec![0.0; self.buffer_size])
+            module_outputs
+                .get(&last_module_id)
+                .map(|mono| mono.iter().map(|&s| (s, s)).collect())
+                .unwrap_or_else(|| vec![(0.0, 0.0); self.buffer_size])
         } else {
-            vec![0.0; self.buffer_size]
+            vec![(0.0, 0.0); self.buffer_size]
+        }
+    }
+
+    /// Builds the serializable snapshot of the current graph. Split out of
+    /// `save_patch` so a caller holding the engine lock (e.g. the realtime
+    /// worker's mutex) only needs it for this quick, in-memory step, and can
+    /// do the actual file write after releasing the lock.
+    pub fn to_patch(&self) -> Patch {
+        let mut modules = Vec::new();
+        for &module_id in self.module_graph.node_weights() {
+            let module = self.modules[&module_id].lock();
+            modules.push(PatchModule {
+                id: module_id,
+                type_name: module.type_name().to_string(),
+                params: module.get_params(),
+                input_count: module.base.inputs.len(),
+            });
+        }
+
+        let mut edges = Vec::new();
+        for edge in self.module_graph.edge_references() {
+            let (source_output, dest_input) = *edge.weight();
+            edges.push(PatchEdge {
+                source: self.module_graph[edge.source()],
+                source_output,
+                dest: self.module_graph[edge.target()],
+                dest_input,
+            });
+        }
+
+        Patch {
+            sample_rate: self.sample_rate,
+            modules,
+            edges,
+            mix_bus: self.mix_bus,
+        }
+    }
+
+    /// Serializes the module graph, its connections, and every module's
+    /// parameters to `path` as JSON.
+    pub fn save_patch(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let json = serde_json::to_string_pretty(&self.to_patch())?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Clears the current graph and rebuilds it from the patch stored at
+    /// `path`, recreating modules via the module registry.
+    pub fn load_patch(&mut self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let data = std::fs::read_to_string(path)?;
+        let patch: Patch = serde_json::from_str(&data)?;
+        self.load_patch_data(patch)
+    }
+
+    /// Same as `load_patch`, but takes an already-deserialized `Patch`. Used
+    /// by `from_patch` so the file read and JSON parse can happen on a
+    /// throwaway engine, off the realtime worker's lock, before the result
+    /// is swapped in.
+    fn load_patch_data(&mut self, patch: Patch) -> Result<(), Box<dyn std::error::Error>> {
+        self.modules.clear();
+        self.module_graph.clear();
+        self.processing_order.clear();
+        self.mix_bus = None;
+
+        // Recreate modules at the sample rate the patch was saved at, since
+        // oscillator/envelope timing is baked into each module at construction.
+        let patch_sample_rate = patch.sample_rate as f32;
+
+        let mut id_map: HashMap<ModuleId, ModuleId> = HashMap::new();
+        for patch_module in &patch.modules {
+            let module = registry::create_module(
+                &patch_module.type_name,
+                patch_sample_rate,
+                self.buffer_size,
+                patch_module.input_count,
+            )
+            .ok_or_else(|| format!("unknown module type in patch: {}", patch_module.type_name))?;
+            for (name, value) in &patch_module.params {
+                module.lock().set_param(name, *value);
+            }
+            let new_id = self.add_module(module);
+            id_map.insert(patch_module.id, new_id);
         }
+
+        for edge in &patch.edges {
+            if let (Some(&source), Some(&dest)) = (id_map.get(&edge.source), id_map.get(&edge.dest)) {
+                self.connect_modules(source, edge.source_output, dest, edge.dest_input);
+            }
+        }
+
+        self.mix_bus = patch.mix_bus.and_then(|old_id| id_map.get(&old_id).copied());
+
+        Ok(())
+    }
+
+    /// Builds a brand-new engine from the patch at `path`, entirely off to
+    /// the side of any existing engine instance. The caller can construct
+    /// this on a background thread and then swap it into the realtime
+    /// worker's `Arc<Mutex<AudioEngine>>` with a single quick lock, instead
+    /// of holding that lock for the file read and full graph rebuild the way
+    /// `load_patch` does in place.
+    pub fn from_patch(path: &str, sample_rate: f64, buffer_size: usize) -> Result<Self, Box<dyn std::error::Error>> {
+        let data = std::fs::read_to_string(path)?;
+        let patch: Patch = serde_json::from_str(&data)?;
+        let mut engine = AudioEngine::new(sample_rate, buffer_size);
+        engine.load_patch_data(patch)?;
+        Ok(engine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mixer::Mixer;
+    use crate::serge_modules::SergeVCO;
+
+    #[test]
+    fn patch_round_trip_preserves_mix_bus_and_params() {
+        let mut engine = AudioEngine::new(44100.0, 4);
+        let vco_id = engine.add_module(SergeVCO::new(44100.0));
+        let mixer_id = engine.add_module(Mixer::new(1, 4));
+        engine.connect_modules(vco_id, 0, mixer_id, 0);
+        engine.set_mix_bus(mixer_id);
+
+        engine
+            .get_module(vco_id)
+            .expect("vco should exist")
+            .lock()
+            .set_param("frequency", 660.0);
+
+        let path = std::env::temp_dir().join("bananavirt_patch_round_trip_test.json");
+        let path_str = path.to_str().unwrap();
+        engine.save_patch(path_str).expect("save_patch should succeed");
+
+        let mut reloaded = AudioEngine::new(44100.0, 4);
+        reloaded.load_patch(path_str).expect("load_patch should succeed");
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(reloaded.modules.len(), 2);
+        assert_eq!(reloaded.module_graph.edge_count(), 1);
+
+        let reloaded_mix_bus = reloaded.mix_bus.expect("mix bus should survive a save/load round trip");
+        let reloaded_mixer = reloaded.modules.get(&reloaded_mix_bus).expect("mix bus module should exist");
+        assert_eq!(reloaded_mixer.lock().type_name(), "Mixer");
+
+        let frequency_round_tripped = reloaded
+            .modules
+            .values()
+            .any(|module| module.lock().get_params().get("frequency").copied() == Some(660.0));
+        assert!(frequency_round_tripped, "VCO frequency should round-trip through the patch");
     }
 }
@@ -1,16 +1,43 @@
 use crate::module::{Module, ModuleBase, ModuleId};
+use std::collections::HashMap;
 use std::sync::Arc;
 use parking_lot::Mutex;
 
+/// Shift amount applied to the free-running tick counter for each of the 16
+/// rate groups (`rate >> 2`). Lower shifts advance the envelope faster.
+const SHIFT_TABLE: [u32; 16] = [15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0];
+
+/// Step size selected by the low two bits of the rate. It is applied on each
+/// increment tick; every increment at a given rate uses the same step.
+const STEP_TABLE: [f32; 4] = [0.015, 0.02, 0.025, 0.03];
+
+/// Level (relative to the 0..1 target) at which a stage is considered
+/// "arrived" — used both to decide when the exponential attack and release
+/// stages end and to calibrate `seconds_to_rate` against the requested
+/// attack/decay/release time. Decay ends at the sustain level instead.
+const ENVELOPE_EPSILON: f32 = 0.001;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CurveMode {
+    Linear,
+    Exponential,
+}
+
 pub struct EnvelopeGenerator {
     base: ModuleBase,
+    sample_rate: f32,
     attack: f32,
     decay: f32,
     sustain: f32,
     release: f32,
+    attack_rate: u8,
+    decay_rate: u8,
+    release_rate: u8,
+    curve_mode: CurveMode,
     stage: EnvelopeStage,
     current_level: f32,
     gate: bool,
+    tick_counter: u32,
 }
 
 enum EnvelopeStage {
@@ -23,24 +50,40 @@ enum EnvelopeStage {
 
 impl EnvelopeGenerator {
     pub fn new(sample_rate: f32) -> Arc<Mutex<dyn Module>> {
-        Arc::new(Mutex::new(Self {
+        let mut eg = Self {
             base: ModuleBase::new("Envelope Generator", 1, 1, 512), // Gate input, envelope output
+            sample_rate,
             attack: 0.01,
             decay: 0.1,
             sustain: 0.5,
             release: 0.2,
+            attack_rate: 0,
+            decay_rate: 0,
+            release_rate: 0,
+            curve_mode: CurveMode::Linear,
             stage: EnvelopeStage::Idle,
             current_level: 0.0,
             gate: false,
-        }))
+            tick_counter: 0,
+        };
+        eg.attack_rate = seconds_to_rate(eg.attack, sample_rate);
+        eg.decay_rate = seconds_to_rate(eg.decay, sample_rate);
+        eg.release_rate = seconds_to_rate(eg.release, sample_rate);
+        Arc::new(Mutex::new(eg))
+    }
+
+    pub fn set_curve_mode(&mut self, mode: CurveMode) {
+        self.curve_mode = mode;
     }
 
     pub fn set_attack(&mut self, attack: f32) {
         self.attack = attack.max(0.001).min(10.0);
+        self.attack_rate = seconds_to_rate(self.attack, self.sample_rate);
     }
 
     pub fn set_decay(&mut self, decay: f32) {
         self.decay = decay.max(0.001).min(10.0);
+        self.decay_rate = seconds_to_rate(self.decay, self.sample_rate);
     }
 
     pub fn set_sustain(&mut self, sustain: f32) {
@@ -49,63 +92,262 @@ impl EnvelopeGenerator {
 
     pub fn set_release(&mut self, release: f32) {
         self.release = release.max(0.001).min(10.0);
+        self.release_rate = seconds_to_rate(self.release, self.sample_rate);
     }
 
     pub fn trigger_on(&mut self) {
         self.gate = true;
         self.stage = EnvelopeStage::Attack;
+        self.tick_counter = 0;
     }
 
     pub fn trigger_off(&mut self) {
         self.gate = false;
         self.stage = EnvelopeStage::Release;
+        self.tick_counter = 0;
+    }
+
+    /// Advances `tick_counter` and reports whether this tick should apply an
+    /// increment step for the given rate (true on ticks where `counter >>
+    /// shift` changes).
+    fn tick(&mut self, rate: u8) -> bool {
+        let shift = SHIFT_TABLE[(rate >> 2) as usize];
+        let before = self.tick_counter >> shift;
+        self.tick_counter += 1;
+        let after = self.tick_counter >> shift;
+        after != before
+    }
+}
+
+/// Converts an attack/decay/release time in seconds to a 0..63 rate by
+/// picking whichever `(shift, step)` combination (as selected by
+/// `SHIFT_TABLE`/`STEP_TABLE`) reaches `ENVELOPE_EPSILON` of the target
+/// closest to the requested number of samples. In `Exponential` mode an
+/// attack from zero takes about the requested time; release starts from the
+/// current level, so it ends somewhat sooner when that level is below 1.0;
+/// decay stops at the sustain level, so it ends after only the part of the
+/// requested time needed to fall from 1.0 to sustain.
+fn seconds_to_rate(seconds: f32, sample_rate: f32) -> u8 {
+    let target_samples = (seconds * sample_rate).max(1.0);
+
+    let mut best_rate = 0u8;
+    let mut best_diff = f32::MAX;
+    for rate in 0u8..=63 {
+        let estimated_samples = estimated_samples_for_rate(rate);
+        let diff = (estimated_samples - target_samples).abs();
+        if diff < best_diff {
+            best_diff = diff;
+            best_rate = rate;
+        }
     }
+    best_rate
+}
+
+/// Number of samples a stage driven at `rate` takes to cross
+/// `ENVELOPE_EPSILON` of its target, given the multiplicative step applied
+/// every `2^shift` ticks.
+fn estimated_samples_for_rate(rate: u8) -> f32 {
+    let shift = SHIFT_TABLE[(rate >> 2) as usize];
+    let step = STEP_TABLE[(rate & 0x3) as usize];
+    let increments_needed = (ENVELOPE_EPSILON.ln() / (1.0 - step).ln()).ceil();
+    increments_needed * (1u32 << shift) as f32
 }
 
 impl Module for EnvelopeGenerator {
     fn process(&mut self, inputs: &[f32], outputs: &mut [f32]) {
-        let sample_time = 1.0 / 44100.0; // Assuming 44.1kHz sample rate
+        let sample_time = 1.0 / self.sample_rate;
 
         for output in outputs.iter_mut() {
-            match self.stage {
-                EnvelopeStage::Idle => {
+            match self.curve_mode {
+                CurveMode::Linear => self.process_linear_sample(sample_time),
+                CurveMode::Exponential => self.process_exponential_sample(),
+            }
+
+            *output = self.current_level * 5.0; // Scale to 0-5V range
+        }
+    }
+
+    fn id(&self) -> ModuleId {
+        self.base.id()
+    }
+
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    fn type_name(&self) -> &'static str {
+        "EnvelopeGenerator"
+    }
+
+    fn get_params(&self) -> HashMap<String, f32> {
+        let mut params = HashMap::new();
+        params.insert("attack".to_string(), self.attack);
+        params.insert("decay".to_string(), self.decay);
+        params.insert("sustain".to_string(), self.sustain);
+        params.insert("release".to_string(), self.release);
+        params.insert(
+            "curve_mode".to_string(),
+            if self.curve_mode == CurveMode::Exponential { 1.0 } else { 0.0 },
+        );
+        params
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) {
+        match name {
+            "attack" => self.set_attack(value),
+            "decay" => self.set_decay(value),
+            "sustain" => self.set_sustain(value),
+            "release" => self.set_release(value),
+            "curve_mode" => {
+                self.set_curve_mode(if value >= 0.5 { CurveMode::Exponential } else { CurveMode::Linear })
+            }
+            "gate" => {
+                if value >= 0.5 {
+                    self.trigger_on();
+                } else {
+                    self.trigger_off();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl EnvelopeGenerator {
+    fn process_linear_sample(&mut self, sample_time: f32) {
+        match self.stage {
+            EnvelopeStage::Idle => {
+                self.current_level = 0.0;
+            }
+            EnvelopeStage::Attack => {
+                self.current_level += sample_time / self.attack;
+                if self.current_level >= 1.0 {
+                    self.current_level = 1.0;
+                    self.stage = EnvelopeStage::Decay;
+                }
+            }
+            EnvelopeStage::Decay => {
+                self.current_level -= sample_time / self.decay * (1.0 - self.sustain);
+                if self.current_level <= self.sustain {
+                    self.current_level = self.sustain;
+                    self.stage = EnvelopeStage::Sustain;
+                }
+            }
+            EnvelopeStage::Sustain => {
+                self.current_level = self.sustain;
+            }
+            EnvelopeStage::Release => {
+                self.current_level -= sample_time / self.release * self.sustain;
+                if self.current_level <= 0.0 {
                     self.current_level = 0.0;
+                    self.stage = EnvelopeStage::Idle;
                 }
-                EnvelopeStage::Attack => {
-                    self.current_level += sample_time / self.attack;
-                    if self.current_level >= 1.0 {
-                        self.current_level = 1.0;
-                        self.stage = EnvelopeStage::Decay;
-                    }
+            }
+        }
+    }
+
+    fn process_exponential_sample(&mut self) {
+        match self.stage {
+            EnvelopeStage::Idle => {
+                self.current_level = 0.0;
+            }
+            EnvelopeStage::Attack => {
+                if self.tick(self.attack_rate) {
+                    let step = STEP_TABLE[(self.attack_rate & 0x3) as usize];
+                    self.current_level += (1.0 - self.current_level) * step;
                 }
-                EnvelopeStage::Decay => {
-                    self.current_level -= sample_time / self.decay * (1.0 - self.sustain);
-                    if self.current_level <= self.sustain {
-                        self.current_level = self.sustain;
-                        self.stage = EnvelopeStage::Sustain;
-                    }
+                if self.current_level >= 1.0 - ENVELOPE_EPSILON {
+                    self.current_level = 1.0;
+                    self.stage = EnvelopeStage::Decay;
+                    self.tick_counter = 0;
                 }
-                EnvelopeStage::Sustain => {
+            }
+            EnvelopeStage::Decay => {
+                if self.tick(self.decay_rate) {
+                    let step = STEP_TABLE[(self.decay_rate & 0x3) as usize];
+                    self.current_level -= self.current_level * step;
+                }
+                if self.current_level <= self.sustain {
                     self.current_level = self.sustain;
+                    self.stage = EnvelopeStage::Sustain;
+                    self.tick_counter = 0;
+                }
+            }
+            EnvelopeStage::Sustain => {
+                self.current_level = self.sustain;
+            }
+            EnvelopeStage::Release => {
+                if self.tick(self.release_rate) {
+                    let step = STEP_TABLE[(self.release_rate & 0x3) as usize];
+                    self.current_level -= self.current_level * step;
                 }
-                EnvelopeStage::Release => {
-                    self.current_level -= sample_time / self.release * self.sustain;
-                    if self.current_level <= 0.0 {
-                        self.current_level = 0.0;
-                        self.stage = EnvelopeStage::Idle;
-                    }
+                if self.current_level <= ENVELOPE_EPSILON {
+                    self.current_level = 0.0;
+                    self.stage = EnvelopeStage::Idle;
+                    self.tick_counter = 0;
                 }
             }
-
-            *output = self.current_level * 5.0; // Scale to 0-5V range
         }
     }
+}
 
-    fn id(&self) -> ModuleId {
-        self.base.id()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seconds_to_rate_is_calibrated_against_real_time() {
+        let sample_rate = 44100.0;
+        for &seconds in &[0.01, 0.1, 0.5, 1.0] {
+            let rate = seconds_to_rate(seconds, sample_rate);
+            let estimated_seconds = estimated_samples_for_rate(rate) / sample_rate;
+            let ratio = estimated_seconds / seconds;
+            assert!(
+                ratio > 0.5 && ratio < 2.0,
+                "seconds_to_rate({seconds}) = {rate} estimates {estimated_seconds}s, off by more than 2x"
+            );
+        }
     }
 
-    fn name(&self) -> &str {
-        self.base.name()
+    #[test]
+    fn exponential_attack_reaches_full_level_within_expected_time() {
+        let sample_rate = 44100.0;
+        let attack_seconds = 0.01;
+        let mut eg = EnvelopeGenerator {
+            base: ModuleBase::new("Envelope Generator", 1, 1, 512),
+            sample_rate,
+            attack: attack_seconds,
+            decay: 0.1,
+            sustain: 0.5,
+            release: 0.2,
+            attack_rate: seconds_to_rate(attack_seconds, sample_rate),
+            decay_rate: 0,
+            release_rate: 0,
+            curve_mode: CurveMode::Exponential,
+            stage: EnvelopeStage::Attack,
+            current_level: 0.0,
+            gate: true,
+            tick_counter: 0,
+        };
+
+        let expected_samples = estimated_samples_for_rate(eg.attack_rate);
+        let max_samples = (expected_samples * 2.0) as u32;
+
+        let mut samples_taken = 0;
+        while !matches!(eg.stage, EnvelopeStage::Decay) && samples_taken < max_samples {
+            eg.process_exponential_sample();
+            samples_taken += 1;
+        }
+
+        assert!(
+            matches!(eg.stage, EnvelopeStage::Decay),
+            "attack did not complete within {max_samples} samples (~{}s)",
+            max_samples as f32 / sample_rate
+        );
+        let elapsed_seconds = samples_taken as f32 / sample_rate;
+        assert!(
+            elapsed_seconds < attack_seconds * 5.0,
+            "attack took {elapsed_seconds}s, expected roughly {attack_seconds}s"
+        );
     }
 }
@@ -0,0 +1,351 @@
+use crate::module::{Module, ModuleBase, ModuleId};
+use std::collections::HashMap;
+use std::sync::Arc;
+use parking_lot::Mutex;
+
+const NUM_OPERATORS: usize = 4;
+const NUM_ALGORITHMS: usize = 8;
+
+/// Modulation matrix: MOD_MATRIX[algorithm][modulator][carrier] is true when
+/// `modulator`'s output feeds into `carrier`'s phase.
+const MOD_MATRIX: [[[bool; NUM_OPERATORS]; NUM_OPERATORS]; NUM_ALGORITHMS] = [
+    // 0: serial chain 1->2->3->4
+    [
+        [false, true, false, false],
+        [false, false, true, false],
+        [false, false, false, true],
+        [false, false, false, false],
+    ],
+    // 1: 1->2, 2->3, 2->4
+    [
+        [false, true, false, false],
+        [false, false, true, true],
+        [false, false, false, false],
+        [false, false, false, false],
+    ],
+    // 2: 1->3, 2->3, 3->4
+    [
+        [false, false, true, false],
+        [false, false, true, false],
+        [false, false, false, true],
+        [false, false, false, false],
+    ],
+    // 3: 1->4, 2->4, 3->4
+    [
+        [false, false, false, true],
+        [false, false, false, true],
+        [false, false, false, true],
+        [false, false, false, false],
+    ],
+    // 4: 1->2, 3->4 (two parallel chains)
+    [
+        [false, true, false, false],
+        [false, false, false, false],
+        [false, false, false, true],
+        [false, false, false, false],
+    ],
+    // 5: 1->2, 1->3, 1->4
+    [
+        [false, true, true, true],
+        [false, false, false, false],
+        [false, false, false, false],
+        [false, false, false, false],
+    ],
+    // 6: 1->2
+    [
+        [false, true, false, false],
+        [false, false, false, false],
+        [false, false, false, false],
+        [false, false, false, false],
+    ],
+    // 7: no modulation, four parallel carriers
+    [
+        [false, false, false, false],
+        [false, false, false, false],
+        [false, false, false, false],
+        [false, false, false, false],
+    ],
+];
+
+/// Scales a modulator's raw ±1 sample into radians of phase deviation
+/// applied to the carrier it feeds (matrix-routed modulation and the
+/// operator-1 self-feedback path alike), giving the modulation depth real
+/// meaning instead of a fixed, shallow index.
+const MODULATION_INDEX: f32 = std::f32::consts::PI * 2.0;
+
+/// Headroom applied to the summed carriers before the ±5V scale, sized for
+/// the worst case (all four operators as carriers, algorithm 7) so no
+/// algorithm can clip even though carrier count varies per algorithm.
+const CARRIER_HEADROOM: f32 = 1.0 / NUM_OPERATORS as f32;
+
+/// CARRIERS[algorithm][operator] is true when that operator's output is
+/// summed into the voice's final output.
+const CARRIERS: [[bool; NUM_OPERATORS]; NUM_ALGORITHMS] = [
+    [false, false, false, true],
+    [false, false, true, true],
+    [false, false, false, true],
+    [false, false, false, true],
+    [false, true, false, true],
+    [false, true, true, true],
+    [false, true, true, true],
+    [true, true, true, true],
+];
+
+struct Operator {
+    phase: f32,
+    multiplier: f32,
+    total_level_db: f32,
+    envelope_gain: f32,
+    last_out: f32,
+}
+
+impl Operator {
+    fn new() -> Self {
+        Operator {
+            phase: 0.0,
+            multiplier: 1.0,
+            total_level_db: 0.0,
+            envelope_gain: 1.0,
+            last_out: 0.0,
+        }
+    }
+
+    fn total_level_gain(&self) -> f32 {
+        10f32.powf(-self.total_level_db / 20.0)
+    }
+}
+
+/// A 4-operator FM synthesizer voice in the spirit of the YM2612, with
+/// selectable routing algorithms and a self-feedback path on operator 1.
+pub struct FmVoice {
+    base: ModuleBase,
+    sample_rate: f32,
+    carrier_freq: f32,
+    operators: [Operator; NUM_OPERATORS],
+    algorithm: usize,
+    feedback_amount: f32,
+}
+
+impl FmVoice {
+    pub fn new(sample_rate: f32) -> Arc<Mutex<dyn Module>> {
+        Arc::new(Mutex::new(Self {
+            base: ModuleBase::new("FM Voice", 1, 1, 512), // pitch CV input, output
+            sample_rate,
+            carrier_freq: 440.0,
+            operators: [Operator::new(), Operator::new(), Operator::new(), Operator::new()],
+            algorithm: 0,
+            feedback_amount: 0.0,
+        }))
+    }
+
+    pub fn set_frequency(&mut self, freq: f32) {
+        self.carrier_freq = freq.max(20.0).min(20000.0);
+    }
+
+    pub fn set_algorithm(&mut self, algorithm: usize) {
+        self.algorithm = algorithm.min(NUM_ALGORITHMS - 1);
+    }
+
+    pub fn set_operator_multiplier(&mut self, operator: usize, multiplier: f32) {
+        if let Some(op) = self.operators.get_mut(operator) {
+            op.multiplier = multiplier.max(0.0);
+        }
+    }
+
+    pub fn set_operator_total_level(&mut self, operator: usize, db: f32) {
+        if let Some(op) = self.operators.get_mut(operator) {
+            op.total_level_db = db.max(0.0);
+        }
+    }
+
+    pub fn set_operator_envelope_gain(&mut self, operator: usize, gain: f32) {
+        if let Some(op) = self.operators.get_mut(operator) {
+            op.envelope_gain = gain.max(0.0).min(1.0);
+        }
+    }
+
+    pub fn set_feedback_amount(&mut self, amount: f32) {
+        self.feedback_amount = amount.max(0.0).min(1.0);
+    }
+}
+
+impl Module for FmVoice {
+    fn process(&mut self, inputs: &[f32], outputs: &mut [f32]) {
+        let pitch_cv = inputs.first().copied().unwrap_or(0.0);
+        let carrier_freq = self.carrier_freq * (1.0 + pitch_cv);
+        let matrix = &MOD_MATRIX[self.algorithm];
+        let carriers = &CARRIERS[self.algorithm];
+
+        for output in outputs.iter_mut() {
+            let mut op_out = [0.0f32; NUM_OPERATORS];
+
+            for i in 0..NUM_OPERATORS {
+                let mut mod_input = 0.0;
+                for j in 0..NUM_OPERATORS {
+                    if matrix[j][i] {
+                        mod_input += op_out[j];
+                    }
+                }
+                if i == 0 {
+                    mod_input += self.feedback_amount * self.operators[0].last_out;
+                }
+                mod_input *= MODULATION_INDEX;
+
+                let op = &mut self.operators[i];
+                op.phase += carrier_freq * op.multiplier / self.sample_rate;
+                if op.phase >= 1.0 {
+                    op.phase -= 1.0;
+                }
+
+                let sample = (2.0 * std::f32::consts::PI * op.phase + mod_input).sin()
+                    * op.envelope_gain
+                    * op.total_level_gain();
+                op.last_out = sample;
+                op_out[i] = sample;
+            }
+
+            let mut mix = 0.0;
+            for i in 0..NUM_OPERATORS {
+                if carriers[i] {
+                    mix += op_out[i];
+                }
+            }
+            *output = mix * CARRIER_HEADROOM * 5.0; // ±5V convention
+        }
+    }
+
+    fn id(&self) -> ModuleId {
+        self.base.id()
+    }
+
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    fn type_name(&self) -> &'static str {
+        "FmVoice"
+    }
+
+    fn get_params(&self) -> HashMap<String, f32> {
+        let mut params = HashMap::new();
+        params.insert("carrier_freq".to_string(), self.carrier_freq);
+        params.insert("algorithm".to_string(), self.algorithm as f32);
+        params.insert("feedback_amount".to_string(), self.feedback_amount);
+        for (i, op) in self.operators.iter().enumerate() {
+            params.insert(format!("op{}_multiplier", i), op.multiplier);
+            params.insert(format!("op{}_total_level_db", i), op.total_level_db);
+            params.insert(format!("op{}_envelope_gain", i), op.envelope_gain);
+        }
+        params
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) {
+        match name {
+            "carrier_freq" => self.set_frequency(value),
+            "algorithm" => self.set_algorithm(value as usize),
+            "feedback_amount" => self.set_feedback_amount(value),
+            _ => {
+                if let Some(rest) = name.strip_prefix("op") {
+                    if let Some((index, field)) = rest.split_once('_') {
+                        if let Ok(operator) = index.parse::<usize>() {
+                            match field {
+                                "multiplier" => self.set_operator_multiplier(operator, value),
+                                "total_level_db" => self.set_operator_total_level(operator, value),
+                                "envelope_gain" => self.set_operator_envelope_gain(operator, value),
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_algorithm_has_at_least_one_carrier() {
+        for (algorithm, carriers) in CARRIERS.iter().enumerate() {
+            assert!(
+                carriers.iter().any(|&is_carrier| is_carrier),
+                "algorithm {algorithm} has no carrier operators"
+            );
+        }
+    }
+
+    #[test]
+    fn mod_matrix_never_routes_an_operator_into_itself() {
+        for (algorithm, matrix) in MOD_MATRIX.iter().enumerate() {
+            for (operator, routes) in matrix.iter().enumerate() {
+                assert!(
+                    !routes[operator],
+                    "algorithm {algorithm} routes operator {operator} into itself"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn carrier_headroom_is_fixed_regardless_of_active_carrier_count() {
+        // A single un-modulated carrier (algorithm 6's operator 1) and the
+        // fully-parallel four-carrier algorithm 7 should sum to the same
+        // per-operator amplitude once headroom is applied, so switching
+        // algorithms doesn't change loudness as a side effect.
+        let sample_rate = 44100.0;
+
+        let single_carrier = FmVoice::new(sample_rate);
+        single_carrier.lock().set_algorithm(6);
+        // Silence operators 0, 2 and 3 so operator 1 is the only audible
+        // carrier and receives no modulation.
+        for op in [0, 2, 3] {
+            single_carrier.lock().set_operator_envelope_gain(op, 0.0);
+        }
+        single_carrier.lock().set_operator_envelope_gain(1, 1.0);
+        let mut single_outputs = [0.0f32; 1];
+        single_carrier.lock().process(&[0.0], &mut single_outputs);
+
+        let four_carriers = FmVoice::new(sample_rate);
+        four_carriers.lock().set_algorithm(7);
+        for op in 0..NUM_OPERATORS {
+            four_carriers.lock().set_operator_envelope_gain(op, 1.0);
+        }
+        let mut four_outputs = [0.0f32; 1];
+        four_carriers.lock().process(&[0.0], &mut four_outputs);
+
+        // Same headroom factor applies whether one or four carriers are
+        // summed, so four identical unit carriers come out ~4x a single one,
+        // not at the same amplitude (that would mean carriers are averaged).
+        assert!(
+            (four_outputs[0].abs() - single_outputs[0].abs() * 4.0).abs() < 0.05,
+            "four-carrier output {} should be ~4x the single-carrier output {}",
+            four_outputs[0],
+            single_outputs[0]
+        );
+    }
+
+    #[test]
+    fn output_stays_within_five_volt_convention_for_every_algorithm() {
+        let sample_rate = 44100.0;
+        for algorithm in 0..NUM_ALGORITHMS {
+            let voice = FmVoice::new(sample_rate);
+            voice.lock().set_algorithm(algorithm);
+            for op in 0..NUM_OPERATORS {
+                voice.lock().set_operator_envelope_gain(op, 1.0);
+            }
+            voice.lock().set_feedback_amount(1.0);
+
+            let mut outputs = [0.0f32; 512];
+            voice.lock().process(&[0.0], &mut outputs);
+
+            for &sample in &outputs {
+                assert!(
+                    sample.abs() <= 5.01,
+                    "algorithm {algorithm} produced {sample}V, outside the ±5V convention"
+                );
+            }
+        }
+    }
+}
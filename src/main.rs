@@ -2,17 +2,28 @@ mod module;
 mod engine;
 mod serge_modules;
 mod envelope_generator;
+mod fm;
+mod mixer;
+mod patch;
+mod registry;
 
-use crate::engine::AudioEngine;
+use crate::engine::{AudioEngine, ParamCommand};
 use crate::serge_modules::{SergeVCO, SergeVCF};
 use crate::envelope_generator::EnvelopeGenerator;
+use crate::mixer::Mixer;
+use crossbeam::queue::ArrayQueue;
+use ringbuf::HeapRb;
 use std::sync::Arc;
 use std::time::Duration;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use crossbeam::channel::{bounded, Receiver, Sender};
 use std::io::{self, BufRead};
 use std::thread;
 
+/// Ring buffer capacity, in stereo frames, given to the realtime audio path.
+/// Sized to a few process() blocks so the worker can stay ahead of the
+/// cpal callback without ever blocking it.
+const RING_BUFFER_FRAMES: usize = 512 * 8;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let sample_rate = 44100.0;
     let buffer_size = 512;
@@ -23,33 +34,48 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let vco = SergeVCO::new(sample_rate as f32);
     let vcf = SergeVCF::new(sample_rate as f32);
     let eg = EnvelopeGenerator::new(sample_rate as f32);
+    let mixer = Mixer::new(1, buffer_size);
 
     let vco_id = engine.add_module(vco);
     let vcf_id = engine.add_module(vcf);
     let eg_id = engine.add_module(eg);
+    let mixer_id = engine.add_module(mixer);
 
     // Connect modules
     engine.connect_modules(vco_id, 0, vcf_id, 0); // VCO output to VCF input
     engine.connect_modules(eg_id, 0, vco_id, 1);  // EG output to VCO FM input
     engine.connect_modules(eg_id, 0, vcf_id, 2);  // EG output to VCF EG input
+    engine.connect_modules(vcf_id, 0, mixer_id, 0); // VCF output to mixer channel 0
+    engine.set_mix_bus(mixer_id);
 
     // Set up audio output
     let host = cpal::default_host();
     let device = host.default_output_device().expect("No output device available");
     let config = device.default_output_config()?;
+    let channels = config.channels() as usize;
 
+    let command_queue = engine.command_queue();
     let engine = Arc::new(parking_lot::Mutex::new(engine));
 
     // Spawn a thread to handle user input
     let engine_clone = engine.clone();
     thread::spawn(move || {
-        handle_user_input(engine_clone, vco_id, vcf_id, eg_id);
+        handle_user_input(
+            engine_clone,
+            command_queue,
+            vco_id,
+            vcf_id,
+            eg_id,
+            mixer_id,
+            sample_rate,
+            buffer_size,
+        );
     });
 
     match config.sample_format() {
-        cpal::SampleFormat::F32 => run::<f32>(&device, &config.into(), engine),
-        cpal::SampleFormat::I16 => run::<i16>(&device, &config.into(), engine),
-        cpal::SampleFormat::U16 => run::<u16>(&device, &config.into(), engine),
+        cpal::SampleFormat::F32 => run::<f32>(&device, &config.into(), engine, channels),
+        cpal::SampleFormat::I16 => run::<i16>(&device, &config.into(), engine, channels),
+        cpal::SampleFormat::U16 => run::<u16>(&device, &config.into(), engine, channels),
         _ => Err("Unsupported sample format".into()),
     }
 }
@@ -58,16 +84,30 @@ fn run<T>(
     device: &cpal::Device,
     config: &cpal::StreamConfig,
     engine: Arc<parking_lot::Mutex<AudioEngine>>,
+    channels: usize,
 ) -> Result<(), Box<dyn std::error::Error>>
 where
     T: cpal::Sample,
 {
-    let (tx, rx) = bounded::<Vec<f32>>(2);
+    let ring = HeapRb::<(f32, f32)>::new(RING_BUFFER_FRAMES);
+    let (mut producer, mut consumer) = ring.split();
+
+    // Dedicated worker thread: the only place that locks the engine for the
+    // realtime path. The cpal callback never touches this lock, so a slow
+    // or contended process() block can no longer glitch the output.
+    thread::spawn(move || loop {
+        let block = engine.lock().process();
+        for frame in block {
+            while producer.push(frame).is_err() {
+                thread::sleep(Duration::from_micros(200));
+            }
+        }
+    });
 
     let stream = device.build_output_stream(
         config,
         move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-            write_data(data, &rx)
+            write_data(data, &mut consumer, channels)
         },
         |err| eprintln!("An error occurred on the output audio stream: {}", err),
         None,
@@ -85,32 +125,65 @@ where
     println!("  eg release <value> - Set EG release time (0.001-10 seconds)");
     println!("  note on           - Trigger note on");
     println!("  note off          - Trigger note off");
+    println!("  mixer gain <ch> <value> - Set mixer channel gain (>=0)");
+    println!("  mixer pan <ch> <value>  - Set mixer channel pan (-1..1)");
+    println!("  save <file>       - Save the current patch to a JSON file");
+    println!("  load <file>       - Load a patch from a JSON file");
     println!("  quit              - Exit the program");
 
     loop {
-        let output = engine.lock().process();
-        tx.send(output).unwrap();
-        std::thread::sleep(Duration::from_millis(10));
+        thread::sleep(Duration::from_secs(3600));
     }
 }
 
-fn write_data<T>(output: &mut [T], rx: &Receiver<Vec<f32>>)
+/// Pops one stereo frame per output frame and interleaves it according to
+/// the device's actual channel count: averaged down to mono, passed through
+/// for stereo, or padded with silence on additional channels.
+fn write_data<T>(output: &mut [T], consumer: &mut ringbuf::HeapConsumer<(f32, f32)>, channels: usize)
 where
     T: cpal::Sample,
 {
-    if let Ok(buffer) = rx.try_recv() {
-        for (out, sample) in output.iter_mut().zip(buffer.iter().cycle()) {
-            *out = T::from::<f32>(*sample);
+    for frame in output.chunks_mut(channels.max(1)) {
+        let (l, r) = consumer.pop().unwrap_or((0.0, 0.0));
+        for (i, out) in frame.iter_mut().enumerate() {
+            let sample = match (channels, i) {
+                (1, _) => (l + r) * 0.5,
+                (_, 0) => l,
+                (_, 1) => r,
+                _ => 0.0,
+            };
+            *out = T::from::<f32>(&sample);
         }
     }
 }
 
+/// Serializes an already-built `Patch` snapshot to `path`. Split out so
+/// `save` can release the engine lock (held only for `to_patch()`) before
+/// doing the file write.
+fn write_patch(patch: &patch::Patch, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let json = serde_json::to_string_pretty(patch)?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
+
 fn handle_user_input(
     engine: Arc<parking_lot::Mutex<AudioEngine>>,
+    command_queue: Arc<ArrayQueue<ParamCommand>>,
     vco_id: module::ModuleId,
     vcf_id: module::ModuleId,
     eg_id: module::ModuleId,
+    mixer_id: module::ModuleId,
+    sample_rate: f64,
+    buffer_size: usize,
 ) {
+    let queue_param = |module_id: module::ModuleId, name: &str, value: f32| {
+        let _ = command_queue.push(ParamCommand {
+            module_id,
+            name: name.to_string(),
+            value,
+        });
+    };
+
     let stdin = io::stdin();
     for line in stdin.lock().lines() {
         if let Ok(input) = line {
@@ -120,77 +193,94 @@ fn handle_user_input(
                     "vco" => {
                         if parts[1] == "freq" {
                             if let Ok(freq) = parts[2].parse::<f32>() {
-                                if let Some(module) = engine.lock().get_module(vco_id) {
-                                    if let Some(vco) = module.lock().downcast_mut::<SergeVCO>() {
-                                        vco.set_frequency(freq);
-                                        println!("VCO frequency set to {} Hz", freq);
-                                    }
-                                }
+                                queue_param(vco_id, "frequency", freq);
+                                println!("VCO frequency set to {} Hz", freq);
                             }
                         }
                     }
                     "vcf" => {
                         if parts[1] == "cutoff" {
                             if let Ok(cutoff) = parts[2].parse::<f32>() {
-                                if let Some(module) = engine.lock().get_module(vcf_id) {
-                                    if let Some(vcf) = module.lock().downcast_mut::<SergeVCF>() {
-                                        vcf.set_cutoff(cutoff);
-                                        println!("VCF cutoff set to {} Hz", cutoff);
-                                    }
-                                }
+                                queue_param(vcf_id, "cutoff", cutoff);
+                                println!("VCF cutoff set to {} Hz", cutoff);
                             }
                         }
                     }
-                    "eg" => {
-                        if let Some(module) = engine.lock().get_module(eg_id) {
-                            if let Some(eg) = module.lock().downcast_mut::<EnvelopeGenerator>() {
-                                match parts[1] {
-                                    "attack" => {
-                                        if let Ok(value) = parts[2].parse::<f32>() {
-                                            eg.set_attack(value);
-                                            println!("EG attack set to {} seconds", value);
-                                        }
-                                    }
-                                    "decay" => {
-                                        if let Ok(value) = parts[2].parse::<f32>() {
-                                            eg.set_decay(value);
-                                            println!("EG decay set to {} seconds", value);
-                                        }
-                                    }
-                                    "sustain" => {
-                                        if let Ok(value) = parts[2].parse::<f32>() {
-                                            eg.set_sustain(value);
-                                            println!("EG sustain set to {}", value);
-                                        }
-                                    }
-                                    "release" => {
-                                        if let Ok(value) = parts[2].parse::<f32>() {
-                                            eg.set_release(value);
-                                            println!("EG release set to {} seconds", value);
-                                        }
-                                    }
-                                    _ => println!("Unknown EG parameter"),
-                                }
+                    "eg" => match parts[1] {
+                        "attack" => {
+                            if let Ok(value) = parts[2].parse::<f32>() {
+                                queue_param(eg_id, "attack", value);
+                                println!("EG attack set to {} seconds", value);
                             }
                         }
-                    }
+                        "decay" => {
+                            if let Ok(value) = parts[2].parse::<f32>() {
+                                queue_param(eg_id, "decay", value);
+                                println!("EG decay set to {} seconds", value);
+                            }
+                        }
+                        "sustain" => {
+                            if let Ok(value) = parts[2].parse::<f32>() {
+                                queue_param(eg_id, "sustain", value);
+                                println!("EG sustain set to {}", value);
+                            }
+                        }
+                        "release" => {
+                            if let Ok(value) = parts[2].parse::<f32>() {
+                                queue_param(eg_id, "release", value);
+                                println!("EG release set to {} seconds", value);
+                            }
+                        }
+                        _ => println!("Unknown EG parameter"),
+                    },
                     _ => println!("Unknown command"),
                 }
             } else if parts.len() == 2 && parts[0] == "note" {
-                if let Some(module) = engine.lock().get_module(eg_id) {
-                    if let Some(eg) = module.lock().downcast_mut::<EnvelopeGenerator>() {
-                        match parts[1] {
-                            "on" => {
-                                eg.trigger_on();
-                                println!("Note on");
-                            }
-                            "off" => {
-                                eg.trigger_off();
-                                println!("Note off");
-                            }
-                            _ => println!("Invalid note command"),
+                match parts[1] {
+                    "on" => {
+                        queue_param(eg_id, "gate", 1.0);
+                        println!("Note on");
+                    }
+                    "off" => {
+                        queue_param(eg_id, "gate", 0.0);
+                        println!("Note off");
+                    }
+                    _ => println!("Invalid note command"),
+                }
+            } else if parts.len() == 4 && parts[0] == "mixer" {
+                if let (Ok(channel), Ok(value)) = (parts[2].parse::<usize>(), parts[3].parse::<f32>()) {
+                    match parts[1] {
+                        "gain" => {
+                            queue_param(mixer_id, &format!("gain{}", channel), value);
+                            println!("Mixer channel {} gain set to {}", channel, value);
+                        }
+                        "pan" => {
+                            queue_param(mixer_id, &format!("pan{}", channel), value);
+                            println!("Mixer channel {} pan set to {}", channel, value);
                         }
+                        _ => println!("Unknown mixer parameter"),
+                    }
+                }
+            } else if parts.len() == 2 && parts[0] == "save" {
+                // Snapshot the graph under a quick lock, then do the file
+                // write after releasing it so the worker thread is never
+                // blocked on disk I/O.
+                let patch = engine.lock().to_patch();
+                match write_patch(&patch, parts[1]) {
+                    Ok(()) => println!("Patch saved to {}", parts[1]),
+                    Err(err) => println!("Failed to save patch: {}", err),
+                }
+            } else if parts.len() == 2 && parts[0] == "load" {
+                // Build the new graph off to the side (file read + module
+                // rebuild), then swap it in with a single quick lock so the
+                // worker thread's process() is never blocked on disk I/O or
+                // graph reconstruction the way an in-place load_patch would.
+                match AudioEngine::from_patch(parts[1], sample_rate, buffer_size) {
+                    Ok(new_engine) => {
+                        *engine.lock() = new_engine;
+                        println!("Patch loaded from {}", parts[1]);
                     }
+                    Err(err) => println!("Failed to load patch: {}", err),
                 }
             } else if input.trim() == "quit" {
                 println!("Exiting...");
@@ -0,0 +1,140 @@
+use crate::module::{Module, ModuleBase, ModuleId};
+use std::collections::HashMap;
+use std::sync::Arc;
+use parking_lot::Mutex;
+
+struct MixerChannel {
+    gain: f32,
+    pan: f32,
+}
+
+/// Sums N module outputs into a stereo mix bus, with per-channel gain and
+/// pan. Output port 0 carries the left channel, port 1 the right, each
+/// `buffer_size` samples long (matching the port-concatenation convention
+/// used elsewhere for multi-port modules).
+pub struct Mixer {
+    base: ModuleBase,
+    channels: Vec<MixerChannel>,
+}
+
+impl Mixer {
+    pub fn new(num_inputs: usize, buffer_size: usize) -> Arc<Mutex<dyn Module>> {
+        Arc::new(Mutex::new(Self {
+            base: ModuleBase::new("Mixer", num_inputs, 2, buffer_size), // N audio inputs, L/R output
+            channels: (0..num_inputs)
+                .map(|_| MixerChannel { gain: 1.0, pan: 0.0 })
+                .collect(),
+        }))
+    }
+
+    pub fn set_gain(&mut self, channel: usize, gain: f32) {
+        if let Some(ch) = self.channels.get_mut(channel) {
+            ch.gain = gain.max(0.0);
+        }
+    }
+
+    pub fn set_pan(&mut self, channel: usize, pan: f32) {
+        if let Some(ch) = self.channels.get_mut(channel) {
+            ch.pan = pan.max(-1.0).min(1.0);
+        }
+    }
+}
+
+impl Module for Mixer {
+    fn process(&mut self, inputs: &[f32], outputs: &mut [f32]) {
+        let buffer_size = outputs.len() / 2;
+        let num_channels = self.channels.len();
+        let (left, right) = outputs.split_at_mut(buffer_size);
+
+        for sample_index in 0..buffer_size {
+            let mut l = 0.0;
+            let mut r = 0.0;
+            for channel in 0..num_channels {
+                let sample = inputs[channel * buffer_size + sample_index];
+                let ch = &self.channels[channel];
+                let left_gain = ch.gain * (1.0 - ch.pan.max(0.0));
+                let right_gain = ch.gain * (1.0 + ch.pan.min(0.0));
+                l += sample * left_gain;
+                r += sample * right_gain;
+            }
+            left[sample_index] = l;
+            right[sample_index] = r;
+        }
+    }
+
+    fn id(&self) -> ModuleId {
+        self.base.id()
+    }
+
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    fn type_name(&self) -> &'static str {
+        "Mixer"
+    }
+
+    fn get_params(&self) -> HashMap<String, f32> {
+        let mut params = HashMap::new();
+        for (i, ch) in self.channels.iter().enumerate() {
+            params.insert(format!("gain{}", i), ch.gain);
+            params.insert(format!("pan{}", i), ch.pan);
+        }
+        params
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) {
+        if let Some(rest) = name.strip_prefix("gain") {
+            if let Ok(channel) = rest.parse::<usize>() {
+                self.set_gain(channel, value);
+            }
+        } else if let Some(rest) = name.strip_prefix("pan") {
+            if let Ok(channel) = rest.parse::<usize>() {
+                self.set_pan(channel, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process_one_channel(pan: f32, gain: f32, sample: f32) -> (f32, f32) {
+        let mixer = Mixer::new(1, 1);
+        mixer.lock().set_gain(0, gain);
+        mixer.lock().set_pan(0, pan);
+
+        let mut outputs = [0.0f32; 2];
+        mixer.lock().process(&[sample], &mut outputs);
+        (outputs[0], outputs[1])
+    }
+
+    #[test]
+    fn centered_pan_sends_equal_gain_to_both_channels() {
+        let (l, r) = process_one_channel(0.0, 1.0, 1.0);
+        assert_eq!(l, 1.0);
+        assert_eq!(r, 1.0);
+    }
+
+    #[test]
+    fn full_left_pan_mutes_the_right_channel() {
+        let (l, r) = process_one_channel(-1.0, 1.0, 1.0);
+        assert_eq!(l, 1.0);
+        assert_eq!(r, 0.0);
+    }
+
+    #[test]
+    fn full_right_pan_mutes_the_left_channel() {
+        let (l, r) = process_one_channel(1.0, 1.0, 1.0);
+        assert_eq!(l, 0.0);
+        assert_eq!(r, 1.0);
+    }
+
+    #[test]
+    fn pan_is_clamped_to_unit_range() {
+        let (l, r) = process_one_channel(5.0, 1.0, 1.0);
+        assert_eq!(l, 0.0);
+        assert_eq!(r, 1.0);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use parking_lot::RwLock;
 use uuid::Uuid;
@@ -8,6 +9,15 @@ pub trait Module: Send + Sync {
     fn process(&mut self, inputs: &[f32], outputs: &mut [f32]);
     fn id(&self) -> ModuleId;
     fn name(&self) -> &str;
+
+    /// The registry key used to recreate this module when loading a patch.
+    fn type_name(&self) -> &'static str;
+
+    /// Snapshot of this module's tweakable parameters, keyed by name.
+    fn get_params(&self) -> HashMap<String, f32>;
+
+    /// Applies a single named parameter, as produced by `get_params`.
+    fn set_param(&mut self, name: &str, value: f32);
 }
 
 pub struct Port {
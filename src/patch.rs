@@ -0,0 +1,33 @@
+use crate::module::ModuleId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// On-disk representation of a single module graph node.
+#[derive(Serialize, Deserialize)]
+pub struct PatchModule {
+    pub id: ModuleId,
+    pub type_name: String,
+    pub params: HashMap<String, f32>,
+    /// Number of input ports the module was built with, needed to recreate
+    /// variable-arity modules like `Mixer` with the right channel count.
+    pub input_count: usize,
+}
+
+/// On-disk representation of a connection between two modules.
+#[derive(Serialize, Deserialize)]
+pub struct PatchEdge {
+    pub source: ModuleId,
+    pub source_output: usize,
+    pub dest: ModuleId,
+    pub dest_input: usize,
+}
+
+/// A full saved patch: every module in the graph plus how they're wired.
+#[derive(Serialize, Deserialize)]
+pub struct Patch {
+    pub sample_rate: f64,
+    pub modules: Vec<PatchModule>,
+    pub edges: Vec<PatchEdge>,
+    /// The module designated as the final stereo mix bus, if any.
+    pub mix_bus: Option<ModuleId>,
+}
@@ -0,0 +1,27 @@
+use crate::envelope_generator::EnvelopeGenerator;
+use crate::fm::FmVoice;
+use crate::mixer::Mixer;
+use crate::module::Module;
+use crate::serge_modules::{SergeVCF, SergeVCO};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// Recreates a module from its `type_name()` key, used when loading a saved
+/// patch. Add an arm here whenever a new `Module` impl should be patchable.
+/// `input_count` is the number of input ports the module was saved with,
+/// needed to rebuild variable-arity modules like `Mixer` faithfully.
+pub fn create_module(
+    type_name: &str,
+    sample_rate: f32,
+    buffer_size: usize,
+    input_count: usize,
+) -> Option<Arc<Mutex<dyn Module>>> {
+    match type_name {
+        "SergeVCO" => Some(SergeVCO::new(sample_rate)),
+        "SergeVCF" => Some(SergeVCF::new(sample_rate)),
+        "EnvelopeGenerator" => Some(EnvelopeGenerator::new(sample_rate)),
+        "FmVoice" => Some(FmVoice::new(sample_rate)),
+        "Mixer" => Some(Mixer::new(input_count, buffer_size)),
+        _ => None,
+    }
+}
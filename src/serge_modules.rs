@@ -1,4 +1,5 @@
 use crate::module::{Module, ModuleBase, ModuleId};
+use std::collections::HashMap;
 use std::sync::Arc;
 use parking_lot::Mutex;
 
@@ -50,6 +51,25 @@ impl Module for SergeVCO {
     fn name(&self) -> &str {
         self.base.name()
     }
+
+    fn type_name(&self) -> &'static str {
+        "SergeVCO"
+    }
+
+    fn get_params(&self) -> HashMap<String, f32> {
+        let mut params = HashMap::new();
+        params.insert("frequency".to_string(), self.frequency);
+        params.insert("fm_amount".to_string(), self.fm_amount);
+        params
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) {
+        match name {
+            "frequency" => self.set_frequency(value),
+            "fm_amount" => self.set_fm_amount(value),
+            _ => {}
+        }
+    }
 }
 
 pub struct SergeVCF {
@@ -101,4 +121,23 @@ impl Module for SergeVCF {
     fn name(&self) -> &str {
         self.base.name()
     }
+
+    fn type_name(&self) -> &'static str {
+        "SergeVCF"
+    }
+
+    fn get_params(&self) -> HashMap<String, f32> {
+        let mut params = HashMap::new();
+        params.insert("cutoff".to_string(), self.cutoff);
+        params.insert("resonance".to_string(), self.resonance);
+        params
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) {
+        match name {
+            "cutoff" => self.set_cutoff(value),
+            "resonance" => self.set_resonance(value),
+            _ => {}
+        }
+    }
 }